@@ -12,6 +12,10 @@ pub enum WireType {
     Bit64 = 1,
     /// string, bytes, embedded messages, packed repeated fields
     Bytes = 2,
+    /// deprecated, start of a group
+    StartGroup = 3,
+    /// deprecated, end of a group
+    EndGroup = 4,
     /// fixed32, sfixed32, float
     Bit32 = 5,
 }
@@ -22,12 +26,18 @@ impl WireType {
             0 => Some(WireType::Varint),
             1 => Some(WireType::Bit64),
             2 => Some(WireType::Bytes),
+            3 => Some(WireType::StartGroup),
+            4 => Some(WireType::EndGroup),
             5 => Some(WireType::Bit32),
             _ => None,
         }
     }
 }
 
+/// The default recursion/depth limit for nested messages and groups, matching
+/// the limit used by the C++ and rust-protobuf implementations.
+pub const DEFAULT_RECURSION_LIMIT: usize = 100;
+
 /// The Error type.
 #[derive(Debug)]
 pub enum Error {
@@ -45,6 +55,11 @@ pub enum Error {
     InvalidUtf8String,
     /// Buffer overflow while encoding.
     BufferOverflow,
+    /// Nested messages or groups exceeded the reader's depth limit.
+    RecursionLimitExceeded,
+    /// An end-group tag was found for a different field number than the
+    /// group currently being skipped.
+    UnmatchedEndGroup(u8),
 }
 
 impl Error {
@@ -61,12 +76,30 @@ impl Error {
 pub struct PbReader<'a> {
     buf: &'a [u8],
     pos: usize,
+    depth: usize,
+    depth_limit: usize,
 }
 
 impl PbReader<'_> {
     /// Create from raw bytes.
     pub fn new<'a>(buf: &'a [u8]) -> PbReader<'a> {
-        PbReader { buf, pos: 0 }
+        PbReader {
+            buf,
+            pos: 0,
+            depth: 0,
+            depth_limit: DEFAULT_RECURSION_LIMIT,
+        }
+    }
+
+    /// Create from raw bytes with a custom recursion/depth limit for nested
+    /// messages and groups.
+    pub fn with_depth_limit<'a>(buf: &'a [u8], depth_limit: usize) -> PbReader<'a> {
+        PbReader {
+            buf,
+            pos: 0,
+            depth: 0,
+            depth_limit,
+        }
     }
 
     /// Is the parsing finished and EOF.
@@ -94,26 +127,72 @@ impl PbReader<'_> {
 
     /// Skip next field, including key and filed value.
     pub fn skip_next_field(&mut self) -> Result<(), Error> {
-        match self.next_key()? {
-            (_, WireType::Varint) => {
+        let (field_number, wire_type) = self.next_key()?;
+        self.skip_value(field_number, wire_type)
+    }
+
+    fn skip_value(&mut self, field_number: u8, wire_type: WireType) -> Result<(), Error> {
+        match wire_type {
+            WireType::Varint => {
                 let _ = self.next_varint()?;
             }
-            (_, WireType::Bytes) => {
+            WireType::Bytes => {
                 let _ = self.next_bytes()?;
             }
-            (_, WireType::Bit32) => {
+            WireType::Bit32 => {
                 let _ = self.next_fixed32()?;
             }
-            (_, WireType::Bit64) => {
+            WireType::Bit64 => {
                 let _ = self.next_fixed64()?;
             }
+            WireType::StartGroup => {
+                self.skip_group(field_number)?;
+            }
+            WireType::EndGroup => {}
         }
         Ok(())
     }
 
+    /// Skip a whole group, recursively skipping fields until the matching
+    /// end-group tag for `field_number` is reached. Returns the position of
+    /// that end-group tag, i.e. the position right after the group's body.
+    fn skip_group(&mut self, field_number: u8) -> Result<usize, Error> {
+        self.depth += 1;
+        if self.depth > self.depth_limit {
+            self.depth -= 1;
+            return Err(Error::RecursionLimitExceeded);
+        }
+        let result = self.skip_group_body(field_number);
+        self.depth -= 1;
+        result
+    }
+
+    fn skip_group_body(&mut self, field_number: u8) -> Result<usize, Error> {
+        loop {
+            let tag_pos = self.pos;
+            let (fnum, wt) = self.next_key()?;
+            if wt == WireType::EndGroup {
+                if fnum == field_number {
+                    return Ok(tag_pos);
+                }
+                // An end-group tag for a different field number means the
+                // input's groups are unbalanced; absorbing it as a no-op
+                // (like a standalone EndGroup field) would silently let
+                // malformed nesting desync the parse instead of surfacing it.
+                return Err(Error::UnmatchedEndGroup(fnum));
+            }
+            self.skip_value(fnum, wt)?;
+        }
+    }
+
     /// Parse a fixed32.
     pub fn next_fixed32(&mut self) -> Result<[u8; 4], Error> {
         if self.pos + 4 > self.buf.len() {
+            // Not enough bytes left to make progress either way, so consume
+            // the remainder: guarantees a caller looping until `is_eof()`
+            // (e.g. the packed-fixed32 iterator) terminates instead of
+            // re-reading the same failing bounds check forever.
+            self.pos = self.buf.len();
             Err(Error::UnexpectedEof)
         } else {
             let mut ret = [0u8; 4];
@@ -126,6 +205,9 @@ impl PbReader<'_> {
     /// Parse a fixed64.
     pub fn next_fixed64(&mut self) -> Result<[u8; 8], Error> {
         if self.pos + 8 > self.buf.len() {
+            // See next_fixed32: consume the remainder so callers looping
+            // until `is_eof()` are guaranteed to terminate.
+            self.pos = self.buf.len();
             Err(Error::UnexpectedEof)
         } else {
             let mut ret = [0u8; 8];
@@ -172,7 +254,17 @@ impl PbReader<'_> {
 
     /// Parse next bytes array as embedded message(sub-field).
     pub fn next_embedded_message(&mut self) -> Result<PbReader<'_>, Error> {
-        self.next_bytes().map(PbReader::new)
+        let depth = self.depth + 1;
+        if depth > self.depth_limit {
+            return Err(Error::RecursionLimitExceeded);
+        }
+        let depth_limit = self.depth_limit;
+        self.next_bytes().map(|buf| PbReader {
+            buf,
+            pos: 0,
+            depth,
+            depth_limit,
+        })
     }
 
     /// Parse next svarint.
@@ -181,6 +273,81 @@ impl PbReader<'_> {
         Ok(varint_to_svarint(val))
     }
 
+    /// Parse next sint32 svarint.
+    pub fn next_svarint32(&mut self) -> Result<i32, Error> {
+        let n = self.next_varint()? as u32;
+        Ok(((n >> 1) as i32) ^ -((n & 1) as i32))
+    }
+
+    /// Parse a fixed32 as a little-endian `u32` (fixed32).
+    pub fn next_fixed_u32(&mut self) -> Result<u32, Error> {
+        self.next_fixed32().map(u32::from_le_bytes)
+    }
+
+    /// Parse a fixed64 as a little-endian `u64` (fixed64).
+    pub fn next_fixed_u64(&mut self) -> Result<u64, Error> {
+        self.next_fixed64().map(u64::from_le_bytes)
+    }
+
+    /// Parse a fixed32 as a little-endian `i32` (sfixed32).
+    pub fn next_sfixed32(&mut self) -> Result<i32, Error> {
+        self.next_fixed32().map(i32::from_le_bytes)
+    }
+
+    /// Parse a fixed64 as a little-endian `i64` (sfixed64).
+    pub fn next_sfixed64(&mut self) -> Result<i64, Error> {
+        self.next_fixed64().map(i64::from_le_bytes)
+    }
+
+    /// Parse a fixed32 as a `f32` (float).
+    pub fn next_float(&mut self) -> Result<f32, Error> {
+        self.next_fixed32().map(f32::from_le_bytes)
+    }
+
+    /// Parse a fixed64 as a `f64` (double).
+    pub fn next_double(&mut self) -> Result<f64, Error> {
+        self.next_fixed64().map(f64::from_le_bytes)
+    }
+
+    /// Parse next bytes array as a packed repeated scalar field.
+    pub fn next_packed_field(&mut self) -> Result<PackedReader<'_>, Error> {
+        self.next_bytes().map(PackedReader::new)
+    }
+
+    /// Parse the next field without interpreting its value, returning the field
+    /// number, wire type, and the exact input slice covering the value (not the
+    /// tag).
+    ///
+    /// For a `StartGroup` field, the "value" is the group's body only — the
+    /// bytes strictly between the start-group and end-group tags, excluding
+    /// both. Because a group has no length prefix, that slice can't be
+    /// reassembled into a valid field on its own: use
+    /// [`Self::next_raw_field_with_key`] paired with
+    /// [`PbWriter::write_raw_field`] to forward a group field verbatim, since
+    /// that variant captures both delimiting tags.
+    pub fn next_raw_field(&mut self) -> Result<(u8, WireType, &[u8]), Error> {
+        let (field_number, wire_type) = self.next_key()?;
+        let value_start = self.pos;
+        let value_end = if wire_type == WireType::StartGroup {
+            self.skip_group(field_number)?
+        } else {
+            self.skip_value(field_number, wire_type)?;
+            self.pos
+        };
+        Ok((field_number, wire_type, &self.buf[value_start..value_end]))
+    }
+
+    /// Like [`Self::next_raw_field`], but the returned slice also includes the
+    /// tag bytes — for a `StartGroup` field, both the start- and end-group
+    /// tags — so it's self-contained and safe to forward with
+    /// [`PbWriter::write_raw_field`] regardless of wire type.
+    pub fn next_raw_field_with_key(&mut self) -> Result<(u8, WireType, &[u8]), Error> {
+        let tag_start = self.pos;
+        let (field_number, wire_type) = self.next_key()?;
+        self.skip_value(field_number, wire_type)?;
+        Ok((field_number, wire_type, &self.buf[tag_start..self.pos]))
+    }
+
     fn peek_next_u8(&self) -> Option<u8> {
         if self.has_next() {
             Some(self.buf[self.pos])
@@ -204,6 +371,106 @@ impl PbReader<'_> {
     }
 }
 
+/// Decode a packed repeated scalar field: a `Bytes` field body that is a
+/// tightly concatenated run of same-type elements with no per-element tags.
+pub struct PackedReader<'a> {
+    reader: PbReader<'a>,
+}
+
+impl<'a> PackedReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        PackedReader {
+            reader: PbReader::new(buf),
+        }
+    }
+
+    /// Is the parsing finished and EOF.
+    pub fn is_eof(&self) -> bool {
+        self.reader.is_eof()
+    }
+
+    /// Iterate the body as varints (int32, int64, uint32, uint64, bool, enum).
+    pub fn next_packed_varints(self) -> PackedVarints<'a> {
+        PackedVarints { inner: self }
+    }
+
+    /// Iterate the body as fixed32 values (fixed32, sfixed32, float).
+    pub fn next_packed_fixed32(self) -> PackedFixed32<'a> {
+        PackedFixed32 { inner: self }
+    }
+
+    /// Iterate the body as fixed64 values (fixed64, sfixed64, double).
+    pub fn next_packed_fixed64(self) -> PackedFixed64<'a> {
+        PackedFixed64 { inner: self }
+    }
+
+    fn read_varint(&mut self) -> Result<u64, Error> {
+        self.reader.next_varint()
+    }
+
+    fn read_fixed32(&mut self) -> Result<[u8; 4], Error> {
+        self.reader.next_fixed32()
+    }
+
+    fn read_fixed64(&mut self) -> Result<[u8; 8], Error> {
+        self.reader.next_fixed64()
+    }
+}
+
+/// Iterator over a packed repeated varint field, yielded by
+/// [`PackedReader::next_packed_varints`].
+pub struct PackedVarints<'a> {
+    inner: PackedReader<'a>,
+}
+
+impl Iterator for PackedVarints<'_> {
+    type Item = Result<u64, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.inner.is_eof() {
+            None
+        } else {
+            Some(self.inner.read_varint())
+        }
+    }
+}
+
+/// Iterator over a packed repeated fixed32 field, yielded by
+/// [`PackedReader::next_packed_fixed32`].
+pub struct PackedFixed32<'a> {
+    inner: PackedReader<'a>,
+}
+
+impl Iterator for PackedFixed32<'_> {
+    type Item = Result<u32, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.inner.is_eof() {
+            None
+        } else {
+            Some(self.inner.read_fixed32().map(u32::from_le_bytes))
+        }
+    }
+}
+
+/// Iterator over a packed repeated fixed64 field, yielded by
+/// [`PackedReader::next_packed_fixed64`].
+pub struct PackedFixed64<'a> {
+    inner: PackedReader<'a>,
+}
+
+impl Iterator for PackedFixed64<'_> {
+    type Item = Result<u64, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.inner.is_eof() {
+            None
+        } else {
+            Some(self.inner.read_fixed64().map(u64::from_le_bytes))
+        }
+    }
+}
+
 /// Encode to raw bytes.
 pub struct PbWriter<'a> {
     buf: &'a mut [u8],
@@ -241,15 +508,26 @@ impl PbWriter<'_> {
         Ok(())
     }
 
+    /// Encode a raw fixed32 value (4 little-endian bytes), with no tag. Used
+    /// for packed repeated fixed32/sfixed32/float fields, where each element
+    /// is written back-to-back without its own key.
+    pub fn write_fixed32(&mut self, value: u32) -> Result<(), Error> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    /// Encode a raw fixed64 value (8 little-endian bytes), with no tag. Used
+    /// for packed repeated fixed64/sfixed64/double fields, where each element
+    /// is written back-to-back without its own key.
+    pub fn write_fixed64(&mut self, value: u64) -> Result<(), Error> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
     /// Encode a varint field.
     pub fn encode_varint_field(&mut self, field_number: u8, value: u64) -> Result<(), Error> {
         // if value == 0 {
         //     return Ok(());
         // }
-        let key = field_number
-            .checked_shl(3)
-            .ok_or(Error::InvalidFieldNumber(field_number))?
-            + WireType::Varint as u8;
+        let key = field_key(field_number, WireType::Varint)?;
         self.write_u8(key)?;
         self.write_varint(value)
     }
@@ -264,10 +542,7 @@ impl PbWriter<'_> {
         // if value.is_empty() {
         //     return Ok(())
         // }
-        let key = field_number
-            .checked_shl(3)
-            .ok_or(Error::InvalidFieldNumber(field_number))?
-            + WireType::Bytes as u8;
+        let key = field_key(field_number, WireType::Bytes)?;
         self.write_u8(key)?;
         self.write_varint(value.len() as _)?;
         self.write_bytes(value)
@@ -278,6 +553,111 @@ impl PbWriter<'_> {
         self.encode_bytes_field(field_number, value.as_bytes())
     }
 
+    /// Encode a sint32 field, zig-zag encoded as a 32-bit varint.
+    pub fn encode_svarint32_field(&mut self, field_number: u8, value: i32) -> Result<(), Error> {
+        let zigzag = ((value << 1) ^ (value >> 31)) as u32;
+        self.encode_varint_field(field_number, zigzag as u64)
+    }
+
+    /// Encode a fixed32 field.
+    pub fn encode_fixed32_field(&mut self, field_number: u8, value: u32) -> Result<(), Error> {
+        let key = field_key(field_number, WireType::Bit32)?;
+        self.write_u8(key)?;
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    /// Encode a fixed64 field.
+    pub fn encode_fixed64_field(&mut self, field_number: u8, value: u64) -> Result<(), Error> {
+        let key = field_key(field_number, WireType::Bit64)?;
+        self.write_u8(key)?;
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    /// Encode a float field.
+    pub fn encode_float_field(&mut self, field_number: u8, value: f32) -> Result<(), Error> {
+        self.encode_fixed32_field(field_number, value.to_bits())
+    }
+
+    /// Encode a double field.
+    pub fn encode_double_field(&mut self, field_number: u8, value: f64) -> Result<(), Error> {
+        self.encode_fixed64_field(field_number, value.to_bits())
+    }
+
+    /// Copy a raw field, as captured by [`PbReader::next_raw_field_with_key`],
+    /// verbatim into the output. Used to forward unknown fields unchanged.
+    pub fn write_raw_field(&mut self, raw: &[u8]) -> Result<(), Error> {
+        self.write_bytes(raw)
+    }
+
+    /// Encode an embedded message field, back-patching its length once `f` has
+    /// written the body.
+    ///
+    /// The length of a nested message isn't known until it's been encoded, so this
+    /// reserves a single byte for the length varint, runs `f` against a sub-writer
+    /// over the remaining buffer, then shifts the body forward if the real length
+    /// needed more than one varint byte and writes the length into the gap.
+    pub fn encode_message_field(
+        &mut self,
+        field_number: u8,
+        f: impl FnOnce(&mut PbWriter) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let fallback_pos = self.pos;
+        let key = field_key(field_number, WireType::Bytes)?;
+        if let Err(err) = self.write_u8(key) {
+            self.pos = fallback_pos;
+            return Err(err);
+        }
+        let start = self.pos;
+        // reserve 1 byte for the length varint, back-patched below
+        if let Err(err) = self.write_u8(0) {
+            self.pos = fallback_pos;
+            return Err(err);
+        }
+        let n = {
+            let mut sub = PbWriter::new(&mut self.buf[start + 1..]);
+            match f(&mut sub) {
+                Ok(()) => sub.pos,
+                Err(err) => {
+                    self.pos = fallback_pos;
+                    return Err(err);
+                }
+            }
+        };
+        self.pos = start + 1 + n;
+        let len_size = varint_len(n as u64);
+        if len_size > 1 {
+            let shift = len_size - 1;
+            if self.pos + shift > self.buf.len() {
+                self.pos = fallback_pos;
+                return Err(Error::BufferOverflow);
+            }
+            self.buf
+                .copy_within(start + 1..start + 1 + n, start + 1 + shift);
+            self.pos += shift;
+        }
+        if let Err(err) = PbWriter::new(&mut self.buf[start..]).write_varint(n as u64) {
+            self.pos = fallback_pos;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Encode a packed repeated field: a single `Bytes` field whose body is the
+    /// back-to-back concatenation of `values`, each written by `write_one`.
+    pub fn encode_packed_field<T: Copy>(
+        &mut self,
+        field_number: u8,
+        values: &[T],
+        mut write_one: impl FnMut(&mut PbWriter, T) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.encode_message_field(field_number, |w| {
+            for &value in values {
+                write_one(w, value)?;
+            }
+            Ok(())
+        })
+    }
+
     /// The raw protobuf bytes encoded.
     pub fn as_bytes(&self) -> &[u8] {
         &self.buf[..self.pos]
@@ -294,7 +674,7 @@ impl PbWriter<'_> {
     }
 
     fn write_bytes(&mut self, val: &[u8]) -> Result<(), Error> {
-        if self.pos + val.len() < self.buf.len() {
+        if self.pos + val.len() <= self.buf.len() {
             self.buf[self.pos..self.pos + val.len()].copy_from_slice(val);
             self.pos += val.len();
             Ok(())
@@ -309,6 +689,28 @@ impl PbWriter<'_> {
     }
 }
 
+/// Build a field tag byte, rejecting field numbers that don't fit the 5 bits
+/// left after the 3-bit wire type. `u8::checked_shl` only reports overflow
+/// once the shift amount itself is out of range (never for our fixed shift
+/// of 3), so the bound has to be checked explicitly instead.
+#[inline]
+fn field_key(field_number: u8, wire_type: WireType) -> Result<u8, Error> {
+    if field_number > 0b11111 {
+        return Err(Error::InvalidFieldNumber(field_number));
+    }
+    Ok((field_number << 3) | wire_type as u8)
+}
+
+#[inline]
+fn varint_len(mut n: u64) -> usize {
+    let mut len = 1;
+    while n >= 0x80 {
+        n >>= 7;
+        len += 1;
+    }
+    len
+}
+
 #[inline]
 fn varint_to_svarint(n: u64) -> i64 {
     if n & 0b1 == 1 {
@@ -322,3 +724,484 @@ fn varint_to_svarint(n: u64) -> i64 {
 fn svarint_to_varint(n: i64) -> u64 {
     ((n << 1) ^ (n >> 63)) as u64
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn svarint32_roundtrips_edge_values() {
+        for &value in &[0i32, 1, -1, i32::MAX, i32::MIN] {
+            let mut out = [0u8; 16];
+            let mut w = PbWriter::new(&mut out);
+            w.encode_svarint32_field(1, value).unwrap();
+
+            let mut r = PbReader::new(w.as_bytes());
+            assert_eq!(r.next_key().unwrap(), (1, WireType::Varint));
+            assert_eq!(r.next_svarint32().unwrap(), value);
+            assert!(r.is_eof());
+        }
+    }
+
+    #[test]
+    fn svarint32_zigzag_minimizes_varint_length_for_small_negatives() {
+        // -1 zigzags to 1, so it should fit in a single varint byte, unlike a
+        // naive two's-complement encoding of -1 as a 64-bit varint.
+        let mut out = [0u8; 16];
+        let mut w = PbWriter::new(&mut out);
+        w.encode_svarint32_field(1, -1).unwrap();
+        assert_eq!(w.as_bytes().len(), 2); // 1 key byte + 1 varint byte
+    }
+
+    #[test]
+    fn encode_fixed_field_fits_a_buffer_sized_to_exactly_match() {
+        // 1 key byte + 4 value bytes == the whole buffer; write_bytes' bound
+        // check must accept a write that exactly fills the remaining space,
+        // not just one that leaves at least a byte spare.
+        let mut out = [0u8; 5];
+        let mut w = PbWriter::new(&mut out);
+        w.encode_fixed32_field(1, 0xdeadbeef).unwrap();
+        assert!(w.is_eof());
+
+        let mut out = [0u8; 9];
+        let mut w = PbWriter::new(&mut out);
+        w.encode_fixed64_field(1, 0xdeadbeef_cafebabe).unwrap();
+        assert!(w.is_eof());
+    }
+
+    #[test]
+    fn encode_field_rejects_field_numbers_over_five_bits() {
+        // 32 (0b100000) doesn't fit in the 5 bits left after the 3-bit wire
+        // type; `u8::checked_shl(3)` alone wouldn't catch this, since 32 << 3
+        // doesn't overflow a u8 shift amount, only the value.
+        let mut out = [0u8; 16];
+        let mut w = PbWriter::new(&mut out);
+        assert!(matches!(
+            w.encode_varint_field(32, 1),
+            Err(Error::InvalidFieldNumber(32))
+        ));
+        assert!(matches!(
+            w.encode_fixed32_field(32, 1),
+            Err(Error::InvalidFieldNumber(32))
+        ));
+        assert!(matches!(
+            w.encode_fixed64_field(32, 1),
+            Err(Error::InvalidFieldNumber(32))
+        ));
+        assert!(matches!(
+            w.encode_svarint32_field(32, 1),
+            Err(Error::InvalidFieldNumber(32))
+        ));
+        assert!(matches!(
+            w.encode_message_field(32, |sub| sub.encode_varint_field(1, 1)),
+            Err(Error::InvalidFieldNumber(32))
+        ));
+        // The writer itself must be untouched by the rejected calls.
+        assert_eq!(w.as_bytes().len(), 0);
+    }
+
+    #[test]
+    fn skip_next_field_skips_a_whole_group() {
+        let mut raw = [0u8; 16];
+        let n = {
+            let mut w = PbWriter::new(&mut raw);
+            w.write_u8((5 << 3) | WireType::StartGroup as u8).unwrap();
+            w.encode_varint_field(1, 42).unwrap();
+            w.write_u8((5 << 3) | WireType::EndGroup as u8).unwrap();
+            w.encode_varint_field(2, 7).unwrap();
+            w.as_bytes().len()
+        };
+
+        let mut r = PbReader::new(&raw[..n]);
+        r.skip_next_field().unwrap();
+        assert_eq!(r.next_key().unwrap(), (2, WireType::Varint));
+        assert_eq!(r.next_varint().unwrap(), 7);
+        assert!(r.is_eof());
+    }
+
+    #[test]
+    fn skip_next_field_errors_on_unmatched_group() {
+        let mut raw = [0u8; 8];
+        let n = {
+            let mut w = PbWriter::new(&mut raw);
+            w.write_u8((5 << 3) | WireType::StartGroup as u8).unwrap();
+            w.encode_varint_field(1, 42).unwrap();
+            w.as_bytes().len()
+        };
+
+        let mut r = PbReader::new(&raw[..n]);
+        assert!(matches!(r.skip_next_field(), Err(Error::Eof)));
+    }
+
+    #[test]
+    fn skip_next_field_errors_on_end_group_for_wrong_field_number() {
+        // Group 5 is closed by an end-group tag for field 6 instead: the
+        // input's groups are unbalanced and must be reported, not silently
+        // absorbed as a no-op valueless field.
+        let mut raw = [0u8; 8];
+        let n = {
+            let mut w = PbWriter::new(&mut raw);
+            w.write_u8((5 << 3) | WireType::StartGroup as u8).unwrap();
+            w.write_u8((6 << 3) | WireType::EndGroup as u8).unwrap();
+            w.as_bytes().len()
+        };
+
+        let mut r = PbReader::new(&raw[..n]);
+        assert!(matches!(
+            r.skip_next_field(),
+            Err(Error::UnmatchedEndGroup(6))
+        ));
+    }
+
+    #[test]
+    fn nested_groups_beyond_depth_limit_are_rejected() {
+        let mut raw = [0u8; 32];
+        let n = {
+            let mut w = PbWriter::new(&mut raw);
+            for _ in 0..3 {
+                w.write_u8((5 << 3) | WireType::StartGroup as u8).unwrap();
+            }
+            for _ in 0..3 {
+                w.write_u8((5 << 3) | WireType::EndGroup as u8).unwrap();
+            }
+            w.as_bytes().len()
+        };
+
+        let mut r = PbReader::with_depth_limit(&raw[..n], 2);
+        assert!(matches!(
+            r.skip_next_field(),
+            Err(Error::RecursionLimitExceeded)
+        ));
+    }
+
+    #[test]
+    fn skip_group_restores_depth_after_recursion_limit_error() {
+        let mut raw = [0u8; 32];
+        let n = {
+            let mut w = PbWriter::new(&mut raw);
+            for _ in 0..3 {
+                w.write_u8((5 << 3) | WireType::StartGroup as u8).unwrap();
+            }
+            for _ in 0..3 {
+                w.write_u8((5 << 3) | WireType::EndGroup as u8).unwrap();
+            }
+            w.as_bytes().len()
+        };
+
+        let mut r = PbReader::with_depth_limit(&raw[..n], 2);
+        assert!(r.skip_next_field().is_err());
+        // The failed skip must unwind depth back to where it started, not
+        // leak it, so later groups/embedded messages aren't starved of
+        // recursion headroom.
+        assert_eq!(r.depth, 0);
+    }
+
+    #[test]
+    fn next_embedded_message_beyond_depth_limit_is_rejected() {
+        // The depth limit applies to nested embedded messages, not just
+        // groups: against a limit of 2, the two nested levels (middle and
+        // innermost) open, and opening a third level from the innermost
+        // message must fail on the depth check, before any bytes are read.
+        let mut innermost = [0u8; 16];
+        let n = {
+            let mut w = PbWriter::new(&mut innermost);
+            w.encode_varint_field(9, 1).unwrap();
+            w.as_bytes().len()
+        };
+        let innermost = &innermost[..n];
+
+        let mut middle = [0u8; 32];
+        let n = {
+            let mut w = PbWriter::new(&mut middle);
+            w.encode_message_field(2, |sub| sub.write_raw_field(innermost))
+                .unwrap();
+            w.as_bytes().len()
+        };
+        let middle = &middle[..n];
+
+        let mut outer = [0u8; 48];
+        let n = {
+            let mut w = PbWriter::new(&mut outer);
+            w.encode_message_field(1, |sub| sub.write_raw_field(middle))
+                .unwrap();
+            w.as_bytes().len()
+        };
+        let outer = &outer[..n];
+
+        let mut r = PbReader::with_depth_limit(outer, 2);
+        r.next_key().unwrap();
+        let mut level1 = r.next_embedded_message().unwrap();
+        level1.next_key().unwrap();
+        let mut level2 = level1.next_embedded_message().unwrap();
+        level2.next_key().unwrap();
+        assert!(matches!(
+            level2.next_embedded_message(),
+            Err(Error::RecursionLimitExceeded)
+        ));
+    }
+
+    #[test]
+    fn raw_field_capture_and_reemit_preserves_unknown_fields() {
+        let mut original = [0u8; 32];
+        let n = {
+            let mut w = PbWriter::new(&mut original);
+            w.encode_varint_field(1, 1).unwrap();
+            w.encode_string_field(2, "hi").unwrap();
+            w.encode_varint_field(3, 3).unwrap();
+            w.as_bytes().len()
+        };
+        let original = &original[..n];
+
+        // Proxy every field, including field 2, which we don't understand here,
+        // using next_raw_field_with_key + write_raw_field to forward it unchanged.
+        let mut out = [0u8; 32];
+        let m = {
+            let mut r = PbReader::new(original);
+            let mut w = PbWriter::new(&mut out);
+            while !r.is_eof() {
+                let (_, _, raw) = r.next_raw_field_with_key().unwrap();
+                w.write_raw_field(raw).unwrap();
+            }
+            w.as_bytes().len()
+        };
+
+        assert_eq!(&out[..m], original);
+    }
+
+    #[test]
+    fn next_raw_field_excludes_the_key_next_raw_field_with_key_includes_it() {
+        let mut raw = [0u8; 8];
+        let n = {
+            let mut w = PbWriter::new(&mut raw);
+            w.encode_varint_field(1, 42).unwrap();
+            w.as_bytes().len()
+        };
+        let raw = &raw[..n];
+
+        let mut r = PbReader::new(raw);
+        let (field_number, wire_type, value_only) = r.next_raw_field().unwrap();
+        assert_eq!(field_number, 1);
+        assert_eq!(wire_type, WireType::Varint);
+        assert_eq!(value_only, &raw[1..]);
+
+        let mut r = PbReader::new(raw);
+        let (_, _, with_key) = r.next_raw_field_with_key().unwrap();
+        assert_eq!(with_key, raw);
+    }
+
+    #[test]
+    fn next_raw_field_returns_only_the_group_body_for_a_group_field() {
+        // StartGroup(5), varint(1, 42), EndGroup(5).
+        let mut raw = [0u8; 8];
+        let n = {
+            let mut w = PbWriter::new(&mut raw);
+            w.write_u8((5 << 3) | WireType::StartGroup as u8).unwrap();
+            w.encode_varint_field(1, 42).unwrap();
+            w.write_u8((5 << 3) | WireType::EndGroup as u8).unwrap();
+            w.as_bytes().len()
+        };
+        let raw = &raw[..n];
+
+        let mut r = PbReader::new(raw);
+        let (field_number, wire_type, value_only) = r.next_raw_field().unwrap();
+        assert_eq!(field_number, 5);
+        assert_eq!(wire_type, WireType::StartGroup);
+        // Neither the start-group tag (raw[0]) nor the end-group tag
+        // (raw[3]) is part of the body.
+        assert_eq!(value_only, &raw[1..3]);
+        assert!(r.is_eof());
+    }
+
+    #[test]
+    fn next_raw_field_with_key_roundtrips_a_group_field_through_write_raw_field() {
+        // StartGroup(5), varint(1, 42), EndGroup(5), followed by a sibling
+        // field that must be untouched by the group capture.
+        let mut original = [0u8; 16];
+        let n = {
+            let mut w = PbWriter::new(&mut original);
+            w.write_u8((5 << 3) | WireType::StartGroup as u8).unwrap();
+            w.encode_varint_field(1, 42).unwrap();
+            w.write_u8((5 << 3) | WireType::EndGroup as u8).unwrap();
+            w.encode_varint_field(2, 7).unwrap();
+            w.as_bytes().len()
+        };
+        let original = &original[..n];
+
+        let mut out = [0u8; 16];
+        let m = {
+            let mut r = PbReader::new(original);
+            let mut w = PbWriter::new(&mut out);
+            while !r.is_eof() {
+                let (_, _, raw) = r.next_raw_field_with_key().unwrap();
+                w.write_raw_field(raw).unwrap();
+            }
+            w.as_bytes().len()
+        };
+
+        // Forwarding via next_raw_field_with_key + write_raw_field must
+        // reproduce the original bytes exactly, including both group tags —
+        // unlike next_raw_field alone, which can't reconstruct a group.
+        assert_eq!(&out[..m], original);
+    }
+
+    fn roundtrip_message_with_body_len(n: usize) {
+        // An arbitrary (non-protobuf-structured) body: encode_message_field only
+        // needs to treat it as opaque bytes to back-patch around correctly.
+        let body = [0xABu8; 20_000];
+        let body = &body[..n];
+
+        let mut out = [0u8; 20_010];
+        let mut w = PbWriter::new(&mut out);
+        w.encode_message_field(1, |sub| sub.write_raw_field(body))
+            .unwrap();
+        let encoded = w.as_bytes();
+        assert_eq!(encoded.len(), 1 + varint_len(n as u64) + n);
+
+        let mut r = PbReader::new(encoded);
+        let (field_number, wire_type) = r.next_key().unwrap();
+        assert_eq!(field_number, 1);
+        assert_eq!(wire_type, WireType::Bytes);
+        assert_eq!(r.next_bytes().unwrap(), body);
+        assert!(r.is_eof());
+    }
+
+    #[test]
+    fn encode_message_field_roundtrips_at_one_byte_len_boundary() {
+        // 127 bytes still fits in a 1-byte length varint.
+        roundtrip_message_with_body_len(127);
+        // 128 bytes needs a 2-byte length varint, forcing the back-patch shift.
+        roundtrip_message_with_body_len(128);
+    }
+
+    #[test]
+    fn encode_message_field_roundtrips_at_two_byte_len_boundary() {
+        // 16383 bytes still fits in a 2-byte length varint.
+        roundtrip_message_with_body_len(16_383);
+        // 16384 bytes needs a 3-byte length varint, forcing a 2-byte shift.
+        roundtrip_message_with_body_len(16_384);
+    }
+
+    #[test]
+    fn encode_packed_field_roundtrips_varints() {
+        let values: [u64; 4] = [0, 1, 127, 300];
+        let mut out = [0u8; 32];
+        let mut w = PbWriter::new(&mut out);
+        w.encode_packed_field(1, &values, |sub, v| sub.write_varint(v))
+            .unwrap();
+
+        let mut r = PbReader::new(w.as_bytes());
+        r.next_key().unwrap();
+        let packed = r.next_packed_field().unwrap();
+        let mut it = packed.next_packed_varints();
+        for expected in values {
+            assert_eq!(it.next().unwrap().unwrap(), expected);
+        }
+        assert!(it.next().is_none());
+    }
+
+    #[test]
+    fn encode_packed_field_roundtrips_fixed32() {
+        let values: [u32; 3] = [1, 0xFFFF_FFFF, 42];
+        let mut out = [0u8; 32];
+        let mut w = PbWriter::new(&mut out);
+        w.encode_packed_field(1, &values, |sub, v| sub.write_fixed32(v))
+            .unwrap();
+
+        let mut r = PbReader::new(w.as_bytes());
+        r.next_key().unwrap();
+        let packed = r.next_packed_field().unwrap();
+        let decoded: [u32; 3] = {
+            let mut it = packed.next_packed_fixed32();
+            [
+                it.next().unwrap().unwrap(),
+                it.next().unwrap().unwrap(),
+                it.next().unwrap().unwrap(),
+            ]
+        };
+        assert_eq!(decoded, values);
+    }
+
+    #[test]
+    fn encode_packed_field_fits_a_buffer_sized_to_exactly_match() {
+        // 1 key byte + 1 length byte + 2*4 value bytes == the whole buffer:
+        // write_fixed32 delegates straight to write_bytes, so this would
+        // spuriously overflow if that off-by-one ever came back.
+        let values: [u32; 2] = [1, 2];
+        let mut out = [0u8; 10];
+        let mut w = PbWriter::new(&mut out);
+        w.encode_packed_field(1, &values, |sub, v| sub.write_fixed32(v))
+            .unwrap();
+        assert!(w.is_eof());
+    }
+
+    #[test]
+    fn packed_fixed32_iterator_terminates_on_truncated_trailing_bytes() {
+        // One full element (4 bytes) plus 2 trailing bytes: not a multiple of
+        // the element width.
+        let body: [u8; 6] = [1, 0, 0, 0, 0xAB, 0xCD];
+        let reader = PackedReader::new(&body);
+        let mut it = reader.next_packed_fixed32();
+
+        assert_eq!(it.next().unwrap().unwrap(), 1);
+        // The truncated trailing bytes yield exactly one error, then the
+        // iterator must terminate rather than looping forever.
+        assert!(matches!(it.next(), Some(Err(Error::UnexpectedEof))));
+        assert!(it.next().is_none());
+    }
+
+    #[test]
+    fn encode_packed_field_roundtrips_double() {
+        let values: [f64; 2] = [1.5, -0.0];
+        let mut out = [0u8; 32];
+        let mut w = PbWriter::new(&mut out);
+        w.encode_packed_field(1, &values, |sub, v| sub.write_fixed64(v.to_bits()))
+            .unwrap();
+
+        let mut r = PbReader::new(w.as_bytes());
+        r.next_key().unwrap();
+        let packed = r.next_packed_field().unwrap();
+        let decoded: [f64; 2] = {
+            let mut it = packed.next_packed_fixed64();
+            [
+                f64::from_bits(it.next().unwrap().unwrap()),
+                f64::from_bits(it.next().unwrap().unwrap()),
+            ]
+        };
+        assert_eq!(decoded[0], values[0]);
+        assert_eq!(decoded[1].to_bits(), values[1].to_bits());
+    }
+
+    #[test]
+    fn encode_message_field_roundtrips_nested_messages() {
+        let mut out = [0u8; 64];
+        let mut w = PbWriter::new(&mut out);
+        w.encode_message_field(1, |outer| {
+            outer.encode_message_field(2, |inner| inner.encode_varint_field(3, 42))
+        })
+        .unwrap();
+
+        let mut r = PbReader::new(w.as_bytes());
+        r.next_key().unwrap();
+        let mut outer = r.next_embedded_message().unwrap();
+        outer.next_key().unwrap();
+        let mut inner = outer.next_embedded_message().unwrap();
+        assert_eq!(inner.next_key().unwrap(), (3, WireType::Varint));
+        assert_eq!(inner.next_varint().unwrap(), 42);
+        assert!(inner.is_eof());
+        assert!(outer.is_eof());
+        assert!(r.is_eof());
+    }
+
+    #[test]
+    fn encode_message_field_rolls_back_pos_on_error() {
+        let mut out = [0u8; 4];
+        let mut w = PbWriter::new(&mut out);
+        let err = w
+            .encode_message_field(1, |sub| sub.encode_bytes_field(1, &[0u8; 100]))
+            .unwrap_err();
+        assert!(matches!(err, Error::BufferOverflow));
+        // The key byte and reserved length byte written before the closure
+        // failed must be rolled back, leaving the writer reusable/clean.
+        assert_eq!(w.as_bytes().len(), 0);
+    }
+}